@@ -4,10 +4,21 @@ use deno_core::error::AnyError;
 use deno_core::op;
 use deno_core::OpState;
 use deno_io::StdFileResource;
+use std::cell::RefCell;
 use std::io::Error;
+use std::rc::Rc;
 
 #[cfg(unix)]
-use nix::sys::termios;
+use rustix::termios;
+
+// Saves the original `Termios` per tty rid so `op_stdin_set_raw` can restore
+// it when raw mode is turned off. deno_io's own `meta_data.tty.mode` slot is
+// typed against the legacy `nix` termios surface, so rather than requiring a
+// coordinated retype of that field we keep the rustix `Termios` in our own op
+// state.
+#[cfg(unix)]
+#[derive(Default)]
+struct SavedTtyModes(std::collections::HashMap<u32, termios::Termios>);
 
 #[cfg(windows)]
 use deno_core::error::custom_error;
@@ -16,6 +27,70 @@ use winapi::shared::minwindef::DWORD;
 #[cfg(windows)]
 use winapi::um::wincon;
 
+// Shared state coordinating `op_stdin_set_raw` with an in-flight stdin read
+// on Windows. A blocked `ReadFile`/`ReadConsole` is still running in the mode
+// that was active when it was issued, so flipping the console mode underneath
+// it leaves the pending read in the stale (cooked) mode. We ask the read loop
+// to cancel and re-arm: `op_stdin_set_raw` raises `cancel_read`, calls
+// `CancelIoEx` to force the blocked read to return, then waits on the condvar
+// until the read loop bumps `ack` to signal it has observed the new mode.
+#[cfg(windows)]
+#[derive(Default)]
+struct WinTtyStateInner {
+  // Raised by `op_stdin_set_raw` to ask an in-flight read to return and
+  // re-issue itself under the mode that is about to be installed.
+  cancel_read: bool,
+  // Incremented by the read loop each time it observes `cancel_read` and
+  // re-arms; `op_stdin_set_raw` waits for this to advance past the value it
+  // sampled before calling `SetConsoleMode`.
+  ack: u64,
+}
+
+#[cfg(windows)]
+#[derive(Clone, Default)]
+pub struct WinTtyState {
+  inner:
+    std::sync::Arc<(std::sync::Mutex<WinTtyStateInner>, std::sync::Condvar)>,
+}
+
+#[cfg(windows)]
+impl WinTtyState {
+  // Raise the cancel flag so the read path re-arms under the new mode, and
+  // return the `ack` value to wait past.
+  fn request_cancel(&self) -> u64 {
+    let (lock, _cvar) = &*self.inner;
+    let mut inner = lock.lock().unwrap();
+    inner.cancel_read = true;
+    inner.ack
+  }
+
+  // Block until the read path acknowledges the mode change (`ack` advances) or
+  // `timeout` elapses. The timeout keeps us from hanging when no read is
+  // actually outstanding to re-arm. The mutex is released while waiting so the
+  // read path can take it to notify.
+  fn wait_ack(&self, ack: u64, timeout: std::time::Duration) {
+    let (lock, cvar) = &*self.inner;
+    let inner = lock.lock().unwrap();
+    let _ = cvar.wait_timeout_while(inner, timeout, |i| i.ack == ack);
+  }
+
+  // Called by the read path: consume a pending cancel request, returning
+  // whether a mode re-arm was asked for.
+  pub fn take_cancel(&self) -> bool {
+    let (lock, _cvar) = &*self.inner;
+    let mut inner = lock.lock().unwrap();
+    std::mem::take(&mut inner.cancel_read)
+  }
+
+  // Called by the read path once it has re-issued its read under the new mode.
+  pub fn notify_ack(&self) {
+    let (lock, cvar) = &*self.inner;
+    let mut inner = lock.lock().unwrap();
+    inner.ack = inner.ack.wrapping_add(1);
+    cvar.notify_all();
+  }
+}
+
 #[cfg(windows)]
 fn get_windows_handle(
   f: &std::fs::File,
@@ -34,7 +109,20 @@ fn get_windows_handle(
 
 deno_core::extension!(
   deno_tty,
-  ops = [op_stdin_set_raw, op_isatty, op_console_size],
+  ops = [
+    op_stdin_set_raw,
+    op_isatty,
+    op_console_size,
+    op_console_next_size,
+    op_set_virtual_terminal,
+    op_read_key
+  ],
+  state = |state| {
+    #[cfg(windows)]
+    state.put(WinTtyState::default());
+    #[cfg(not(windows))]
+    let _ = state;
+  },
   customizer = |ext: &mut deno_core::ExtensionBuilder| {
     ext.force_op_registration();
   },
@@ -60,7 +148,30 @@ fn mode_raw_input_off(original_mode: DWORD) -> DWORD {
   original_mode & !wincon::ENABLE_VIRTUAL_TERMINAL_INPUT | COOKED_MODE
 }
 
-#[op(fast)]
+// cbreak is the middle ground between cooked and raw: line editing and echo
+// are off, but the system still processes control keys so Ctrl-C/Ctrl-Z keep
+// generating signals (the Unix path leaves `ISIG` set for the same reason).
+// Unlike raw mode we therefore keep `ENABLE_PROCESSED_INPUT` and only clear
+// the line-input/echo bits.
+#[cfg(windows)]
+const CBREAK_MODE: DWORD =
+  wincon::ENABLE_LINE_INPUT | wincon::ENABLE_ECHO_INPUT;
+
+#[cfg(windows)]
+fn mode_cbreak_input_on(original_mode: DWORD) -> DWORD {
+  original_mode & !CBREAK_MODE
+    | wincon::ENABLE_VIRTUAL_TERMINAL_INPUT
+    | wincon::ENABLE_PROCESSED_INPUT
+}
+
+#[cfg(windows)]
+fn mode_cbreak_input_off(original_mode: DWORD) -> DWORD {
+  original_mode & !wincon::ENABLE_VIRTUAL_TERMINAL_INPUT | CBREAK_MODE
+}
+
+// Not a fast op: on Windows it may briefly block waiting for an in-flight
+// `op_read_key` to acknowledge the mode switch (see `WinTtyState`).
+#[op]
 fn op_stdin_set_raw(
   state: &mut OpState,
   is_raw: bool,
@@ -77,12 +188,12 @@ fn op_stdin_set_raw(
   {
     use std::os::windows::io::AsRawHandle;
     use winapi::shared::minwindef::FALSE;
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
     use winapi::um::consoleapi;
     use winapi::um::handleapi;
+    use winapi::um::ioapiset;
 
-    if cbreak {
-      return Err(deno_core::error::not_supported());
-    }
+    let win_tty_state = state.borrow::<WinTtyState>().clone();
 
     StdFileResource::with_file(state, rid, move |std_file| {
       let handle = std_file.as_raw_handle();
@@ -100,71 +211,117 @@ fn op_stdin_set_raw(
         return Err(Error::last_os_error().into());
       }
 
-      let new_mode = if is_raw {
-        mode_raw_input_on(original_mode)
-      } else {
-        mode_raw_input_off(original_mode)
+      let new_mode = match (is_raw, cbreak) {
+        (true, false) => mode_raw_input_on(original_mode),
+        (true, true) => mode_cbreak_input_on(original_mode),
+        (false, true) => mode_cbreak_input_off(original_mode),
+        (false, false) => mode_raw_input_off(original_mode),
       };
 
+      // Force any read that is already blocked on stdin to return so it can
+      // re-issue itself under `new_mode`; without this the pending read stays
+      // in the old mode and swallows the first keystrokes after the switch.
+      // `op_read_key`'s blocking read loop observes `take_cancel`, re-arms
+      // under the new mode, and calls `notify_ack`; we wait for that here.
+      let ack = win_tty_state.request_cancel();
+      // SAFETY: winapi call; `handle` is a live console handle.
+      let cancelled =
+        unsafe { ioapiset::CancelIoEx(handle, std::ptr::null_mut()) } != FALSE;
+      // ERROR_NOT_FOUND means nothing was in flight — treat it as success.
+      if !cancelled {
+        let err = Error::last_os_error();
+        if err.raw_os_error() != Some(ERROR_NOT_FOUND as i32) {
+          return Err(err.into());
+        }
+      }
+
       // SAFETY: winapi call
       if unsafe { consoleapi::SetConsoleMode(handle, new_mode) } == FALSE {
         return Err(Error::last_os_error().into());
       }
 
+      // Only a cancelled read re-arms and acknowledges. The bounded wait keeps
+      // us from hanging if the reader was between reads (or none is active).
+      if cancelled {
+        win_tty_state.wait_ack(ack, std::time::Duration::from_millis(250));
+      }
+
       Ok(())
     })
   }
   #[cfg(unix)]
   {
-    use std::os::unix::io::AsRawFd;
+    use termios::ControlModes;
+    use termios::InputModes;
+    use termios::LocalModes;
+    use termios::OptionalActions;
+    use termios::SpecialCodeIndex;
+
+    // The update to apply to our saved-mode table once the blocking fd work is
+    // done (we cannot borrow op state while `with_file` holds it).
+    enum ModeUpdate {
+      Save(termios::Termios),
+      Clear,
+      Keep,
+    }
+
+    if !state.has::<SavedTtyModes>() {
+      state.put(SavedTtyModes::default());
+    }
+    let saved = state.borrow::<SavedTtyModes>().0.get(&rid).cloned();
+
+    let update = StdFileResource::with_file(state, rid, move |std_file| {
+      if is_raw {
+        // Derive raw mode from the saved original (or the current mode the
+        // first time) so repeated calls stay idempotent.
+        let original = match saved {
+          Some(mode) => mode,
+          None => termios::tcgetattr(std_file)?,
+        };
+        let mut raw = original.clone();
 
-    StdFileResource::with_file_and_metadata(
-      state,
-      rid,
-      move |std_file, meta_data| {
-        let raw_fd = std_file.as_raw_fd();
-
-        if is_raw {
-          let mut raw = {
-            let mut meta_data = meta_data.lock();
-            let maybe_tty_mode = &mut meta_data.tty.mode;
-            if maybe_tty_mode.is_none() {
-              // Save original mode.
-              let original_mode = termios::tcgetattr(raw_fd)?;
-              maybe_tty_mode.replace(original_mode);
-            }
-            maybe_tty_mode.clone().unwrap()
-          };
-
-          raw.input_flags &= !(termios::InputFlags::BRKINT
-            | termios::InputFlags::ICRNL
-            | termios::InputFlags::INPCK
-            | termios::InputFlags::ISTRIP
-            | termios::InputFlags::IXON);
-
-          raw.control_flags |= termios::ControlFlags::CS8;
-
-          raw.local_flags &= !(termios::LocalFlags::ECHO
-            | termios::LocalFlags::ICANON
-            | termios::LocalFlags::IEXTEN);
-          if !cbreak {
-            raw.local_flags &= !(termios::LocalFlags::ISIG);
-          }
-          raw.control_chars[termios::SpecialCharacterIndices::VMIN as usize] =
-            1;
-          raw.control_chars[termios::SpecialCharacterIndices::VTIME as usize] =
-            0;
-          termios::tcsetattr(raw_fd, termios::SetArg::TCSADRAIN, &raw)?;
-        } else {
-          // Try restore saved mode.
-          if let Some(mode) = meta_data.lock().tty.mode.take() {
-            termios::tcsetattr(raw_fd, termios::SetArg::TCSADRAIN, &mode)?;
-          }
+        raw.input_modes &= !(InputModes::BRKINT
+          | InputModes::ICRNL
+          | InputModes::INPCK
+          | InputModes::ISTRIP
+          | InputModes::IXON);
+
+        raw.control_modes |= ControlModes::CS8;
+
+        raw.local_modes &=
+          !(LocalModes::ECHO | LocalModes::ICANON | LocalModes::IEXTEN);
+        if !cbreak {
+          raw.local_modes &= !LocalModes::ISIG;
         }
+        raw.special_codes[SpecialCodeIndex::VMIN] = 1;
+        raw.special_codes[SpecialCodeIndex::VTIME] = 0;
+        termios::tcsetattr(std_file, OptionalActions::Drain, &raw)?;
+
+        Ok(ModeUpdate::Save(original))
+      } else if let Some(mode) = saved {
+        // Try restore saved mode.
+        termios::tcsetattr(std_file, OptionalActions::Drain, &mode)?;
+        Ok(ModeUpdate::Clear)
+      } else {
+        Ok(ModeUpdate::Keep)
+      }
+    })?;
 
-        Ok(())
-      },
-    )
+    match update {
+      ModeUpdate::Save(mode) => {
+        state
+          .borrow_mut::<SavedTtyModes>()
+          .0
+          .entry(rid)
+          .or_insert(mode);
+      }
+      ModeUpdate::Clear => {
+        state.borrow_mut::<SavedTtyModes>().0.remove(&rid);
+      }
+      ModeUpdate::Keep => {}
+    }
+
+    Ok(())
   }
 }
 
@@ -193,50 +350,691 @@ fn op_isatty(
     }
     #[cfg(unix)]
     {
-      use std::os::unix::io::AsRawFd;
-      let raw_fd = std_file.as_raw_fd();
-      // TODO(bartlomieju):
-      #[allow(clippy::undocumented_unsafe_blocks)]
-      {
-        out[0] = unsafe { libc::isatty(raw_fd as libc::c_int) == 1 } as u8;
-      }
+      out[0] = termios::isatty(std_file) as u8;
     }
     Ok(())
   })
 }
 
+// Turn ANSI/virtual-terminal processing on an output handle on or off. On
+// legacy Windows consoles escape sequences are not interpreted until
+// `ENABLE_VIRTUAL_TERMINAL_PROCESSING` is set, so this mirrors how the
+// `console` crate bootstraps color/cursor output. Returns whether VT output is
+// active afterwards; on Unix it is always a no-op that reports `true`.
+#[op(fast)]
+fn op_set_virtual_terminal(
+  state: &mut OpState,
+  rid: u32,
+  enable: bool,
+) -> Result<bool, AnyError> {
+  StdFileResource::with_file(state, rid, move |std_file| {
+    #[cfg(windows)]
+    {
+      use winapi::shared::minwindef::FALSE;
+      use winapi::um::consoleapi;
+      use winapi::um::wincon;
+
+      const VT_FLAGS: DWORD = wincon::ENABLE_VIRTUAL_TERMINAL_PROCESSING
+        | wincon::DISABLE_NEWLINE_AUTO_RETURN;
+
+      let handle = get_windows_handle(std_file)?;
+      let mut mode: DWORD = 0;
+      // SAFETY: winapi call
+      if unsafe { consoleapi::GetConsoleMode(handle, &mut mode) } == FALSE {
+        return Err(Error::last_os_error().into());
+      }
+
+      let new_mode = if enable {
+        mode | VT_FLAGS
+      } else {
+        mode & !VT_FLAGS
+      };
+      // SAFETY: winapi call
+      if unsafe { consoleapi::SetConsoleMode(handle, new_mode) } == FALSE {
+        return Err(Error::last_os_error().into());
+      }
+
+      Ok(enable)
+    }
+    #[cfg(unix)]
+    {
+      let _ = (std_file, enable);
+      Ok(true)
+    }
+  })
+}
+
+// A decoded terminal key. Printable input is carried as `Char`; everything
+// else is a named key so JS callers never have to parse escape sequences.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+pub enum Key {
+  Char(char),
+  Enter,
+  Backspace,
+  Tab,
+  Esc,
+  Up,
+  Down,
+  Left,
+  Right,
+  Home,
+  End,
+  PageUp,
+  PageDown,
+  Delete,
+  F(u8),
+}
+
+// Per-tty decoder state put into `OpState`. The lookup table maps escape
+// sequences to keys and is built once from the terminal's terminfo entry
+// (falling back to the common xterm table); `buffers` holds the bytes of a
+// multi-byte sequence that arrived split across reads.
+#[derive(Default)]
+struct KeyDecoder {
+  buffers: std::collections::HashMap<u32, Vec<u8>>,
+  #[cfg(unix)]
+  table: Vec<(Vec<u8>, Key)>,
+}
+
+#[cfg(unix)]
+impl KeyDecoder {
+  fn new() -> Self {
+    KeyDecoder {
+      buffers: std::collections::HashMap::new(),
+      table: load_terminfo_keys().unwrap_or_else(xterm_keys),
+    }
+  }
+}
+
+#[cfg(windows)]
+impl KeyDecoder {
+  fn new() -> Self {
+    KeyDecoder::default()
+  }
+}
+
+// The common xterm/VT100 sequence table, used when no terminfo entry is found.
+#[cfg(unix)]
+fn xterm_keys() -> Vec<(Vec<u8>, Key)> {
+  vec![
+    (b"\x1b[A".to_vec(), Key::Up),
+    (b"\x1b[B".to_vec(), Key::Down),
+    (b"\x1b[C".to_vec(), Key::Right),
+    (b"\x1b[D".to_vec(), Key::Left),
+    (b"\x1b[H".to_vec(), Key::Home),
+    (b"\x1b[F".to_vec(), Key::End),
+    (b"\x1bOH".to_vec(), Key::Home),
+    (b"\x1bOF".to_vec(), Key::End),
+    (b"\x1b[1~".to_vec(), Key::Home),
+    (b"\x1b[4~".to_vec(), Key::End),
+    (b"\x1b[3~".to_vec(), Key::Delete),
+    (b"\x1b[5~".to_vec(), Key::PageUp),
+    (b"\x1b[6~".to_vec(), Key::PageDown),
+    (b"\x1bOP".to_vec(), Key::F(1)),
+    (b"\x1bOQ".to_vec(), Key::F(2)),
+    (b"\x1bOR".to_vec(), Key::F(3)),
+    (b"\x1bOS".to_vec(), Key::F(4)),
+    (b"\x1b[15~".to_vec(), Key::F(5)),
+    (b"\x1b[17~".to_vec(), Key::F(6)),
+    (b"\x1b[18~".to_vec(), Key::F(7)),
+    (b"\x1b[19~".to_vec(), Key::F(8)),
+    (b"\x1b[20~".to_vec(), Key::F(9)),
+    (b"\x1b[21~".to_vec(), Key::F(10)),
+    (b"\x1b[23~".to_vec(), Key::F(11)),
+    (b"\x1b[24~".to_vec(), Key::F(12)),
+  ]
+}
+
+// Read the compiled terminfo entry for `$TERM` and turn its `key_*` string
+// capabilities into a sequence->key table. Returns `None` if the entry cannot
+// be located or parsed, in which case callers fall back to `xterm_keys`.
+#[cfg(unix)]
+fn load_terminfo_keys() -> Option<Vec<(Vec<u8>, Key)>> {
+  // (string-capability index, key) pairs, using the ncurses term.h offsets.
+  const CAPS: &[(usize, Key)] = &[
+    (87, Key::Up),
+    (61, Key::Down),
+    (79, Key::Left),
+    (83, Key::Right),
+    (76, Key::Home),
+    (164, Key::End),
+    (82, Key::PageUp),
+    (81, Key::PageDown),
+    (59, Key::Delete),
+    (55, Key::Backspace),
+    (66, Key::F(1)),
+    (67, Key::F(10)),
+    (68, Key::F(2)),
+    (69, Key::F(3)),
+    (70, Key::F(4)),
+    (71, Key::F(5)),
+    (72, Key::F(6)),
+    (73, Key::F(7)),
+    (74, Key::F(8)),
+    (75, Key::F(9)),
+    (216, Key::F(11)),
+    (217, Key::F(12)),
+  ];
+
+  let term = std::env::var("TERM").ok()?;
+  let data = read_terminfo_file(&term)?;
+  let strings = parse_terminfo_strings(&data)?;
+
+  let mut table = Vec::new();
+  for &(idx, ref key) in CAPS {
+    if let Some(Some(seq)) = strings.get(idx) {
+      if !seq.is_empty() {
+        table.push((seq.clone(), key.clone()));
+      }
+    }
+  }
+  if table.is_empty() {
+    None
+  } else {
+    Some(table)
+  }
+}
+
+// Locate the compiled terminfo file for `term` across the usual search paths.
+#[cfg(unix)]
+fn read_terminfo_file(term: &str) -> Option<Vec<u8>> {
+  let first = term.chars().next()?;
+  let mut dirs: Vec<std::path::PathBuf> = Vec::new();
+  if let Ok(dir) = std::env::var("TERMINFO") {
+    dirs.push(dir.into());
+  }
+  if let Ok(home) = std::env::var("HOME") {
+    dirs.push(std::path::Path::new(&home).join(".terminfo"));
+  }
+  for dir in ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"] {
+    dirs.push(dir.into());
+  }
+  for dir in dirs {
+    // Entries live under either a single-letter or a two-hex-digit directory.
+    let candidates = [
+      dir.join(first.to_string()).join(term),
+      dir.join(format!("{:02x}", first as u32)).join(term),
+    ];
+    for path in candidates {
+      if let Ok(data) = std::fs::read(&path) {
+        return Some(data);
+      }
+    }
+  }
+  None
+}
+
+// Parse the string-capability section of a compiled terminfo file, returning a
+// vector indexed by capability number (`None` where the capability is absent).
+#[cfg(unix)]
+fn parse_terminfo_strings(data: &[u8]) -> Option<Vec<Option<Vec<u8>>>> {
+  fn read_i16(data: &[u8], at: usize) -> Option<i16> {
+    let bytes = data.get(at..at + 2)?;
+    Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+  }
+
+  let magic = read_i16(data, 0)?;
+  // 0o0432 is the legacy 16-bit format; 0o1036 stores numbers as 32-bit ints.
+  let num_width = match magic {
+    0o0432 => 2,
+    0o1036 => 4,
+    _ => return None,
+  };
+
+  let names_size = read_i16(data, 2)? as usize;
+  let bool_count = read_i16(data, 4)? as usize;
+  let num_count = read_i16(data, 6)? as usize;
+  let str_count = read_i16(data, 8)? as usize;
+  let str_size = read_i16(data, 10)? as usize;
+
+  let mut offset = 12 + names_size + bool_count;
+  // The numbers section is aligned to an even byte boundary.
+  if offset % 2 != 0 {
+    offset += 1;
+  }
+  offset += num_count * num_width;
+
+  let str_offsets = offset;
+  let str_table = str_offsets + str_count * 2;
+  if str_table + str_size > data.len() {
+    return None;
+  }
+
+  let mut caps = Vec::with_capacity(str_count);
+  for i in 0..str_count {
+    let off = read_i16(data, str_offsets + i * 2)?;
+    if off < 0 {
+      caps.push(None);
+      continue;
+    }
+    let start = str_table + off as usize;
+    // A hostile/truncated entry can point past the string table; bail to the
+    // xterm fallback rather than panicking on an out-of-order slice.
+    if start > str_table + str_size || start > data.len() {
+      return None;
+    }
+    let end = data[start..str_table + str_size]
+      .iter()
+      .position(|&b| b == 0)
+      .map(|p| start + p)?;
+    caps.push(Some(data[start..end].to_vec()));
+  }
+  Some(caps)
+}
+
+#[cfg(unix)]
+enum Decoded {
+  // A complete key was decoded, consuming `usize` leading bytes.
+  Key(Key, usize),
+  // The buffer holds the start of a recognised sequence; read more bytes.
+  NeedMore,
+}
+
+// Decode the first key from `buf`. `ESC` alone is reported by the caller once
+// its short read window elapses; here a lone `ESC` is treated as incomplete.
+#[cfg(unix)]
+fn decode_unix(buf: &[u8], table: &[(Vec<u8>, Key)]) -> Decoded {
+  match buf.first() {
+    None => Decoded::NeedMore,
+    Some(0x0d) | Some(0x0a) => Decoded::Key(Key::Enter, 1),
+    Some(0x7f) | Some(0x08) => Decoded::Key(Key::Backspace, 1),
+    Some(0x09) => Decoded::Key(Key::Tab, 1),
+    Some(0x1b) => {
+      // Longest table entry that fully matches wins.
+      let best = table
+        .iter()
+        .filter(|(seq, _)| buf.starts_with(seq))
+        .max_by_key(|(seq, _)| seq.len());
+      if let Some((seq, key)) = best {
+        return Decoded::Key(key.clone(), seq.len());
+      }
+      // Still the prefix of a known sequence? wait for the rest.
+      if table.iter().any(|(seq, _)| seq.starts_with(buf)) {
+        return Decoded::NeedMore;
+      }
+      // ESC followed by an unrecognised byte: hand back a bare Esc and keep
+      // the remaining bytes for the next call.
+      if buf.len() == 1 {
+        Decoded::NeedMore
+      } else {
+        Decoded::Key(Key::Esc, 1)
+      }
+    }
+    Some(_) => match decode_utf8(buf) {
+      Some((ch, n)) => Decoded::Key(Key::Char(ch), n),
+      None => Decoded::NeedMore,
+    },
+  }
+}
+
+// Decode one UTF-8 scalar from the front of `buf`, returning the char and its
+// byte length, or `None` if the buffer holds only part of a sequence.
+#[cfg(unix)]
+fn decode_utf8(buf: &[u8]) -> Option<(char, usize)> {
+  let len = match buf[0] {
+    b if b < 0x80 => 1,
+    b if b >> 5 == 0b110 => 2,
+    b if b >> 4 == 0b1110 => 3,
+    b if b >> 3 == 0b11110 => 4,
+    _ => return Some((char::from(buf[0]), 1)),
+  };
+  let slice = buf.get(..len)?;
+  match std::str::from_utf8(slice) {
+    Ok(s) => s.chars().next().map(|c| (c, len)),
+    Err(_) => Some((char::REPLACEMENT_CHARACTER, 1)),
+  }
+}
+
+// Block until the fd is readable or `timeout_ms` elapses; `false` on timeout.
+#[cfg(unix)]
+fn poll_readable(fd: std::os::unix::io::RawFd, timeout_ms: i32) -> bool {
+  let mut pfd = libc::pollfd {
+    fd,
+    events: libc::POLLIN,
+    revents: 0,
+  };
+  // SAFETY: libc call over a single well-formed pollfd.
+  let n = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+  n > 0 && (pfd.revents & libc::POLLIN) != 0
+}
+
+// Read and decode a single key from the tty resource `rid`. Requires the tty
+// to already be in raw mode. Returns one key per call, buffering any bytes of
+// a partially read multi-byte sequence for the next call. This is an async op
+// whose blocking `poll`/`read` runs on a blocking thread so it never freezes
+// the event loop while waiting for a keystroke. On Windows `rid` is not used:
+// keys are always read from the process's standard input console handle.
+#[op]
+async fn op_read_key(
+  state: Rc<RefCell<OpState>>,
+  rid: u32,
+) -> Result<Key, AnyError> {
+  {
+    let mut state = state.borrow_mut();
+    if !state.has::<KeyDecoder>() {
+      state.put(KeyDecoder::new());
+    }
+  }
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::io::AsRawFd;
+
+    // Pull everything the blocking read needs out of op state up front; the
+    // blocking closure runs off-thread and must not touch `OpState`.
+    let (table, buf, raw_fd) = {
+      let mut state = state.borrow_mut();
+      let table = state.borrow::<KeyDecoder>().table.clone();
+      let buf = std::mem::take(
+        state
+          .borrow_mut::<KeyDecoder>()
+          .buffers
+          .entry(rid)
+          .or_default(),
+      );
+      let raw_fd = StdFileResource::with_file(&mut state, rid, |std_file| {
+        Ok(std_file.as_raw_fd())
+      })?;
+      (table, buf, raw_fd)
+    };
+
+    let (key, buf) =
+      tokio::task::spawn_blocking(move || read_key_unix(raw_fd, &table, buf))
+        .await??;
+
+    // Stash any leftover bytes for the next call.
+    state
+      .borrow_mut()
+      .borrow_mut::<KeyDecoder>()
+      .buffers
+      .insert(rid, buf);
+    Ok(key)
+  }
+
+  #[cfg(windows)]
+  {
+    let _ = rid;
+    let win_tty_state = state.borrow().borrow::<WinTtyState>().clone();
+    tokio::task::spawn_blocking(move || read_key_windows(&win_tty_state))
+      .await?
+  }
+}
+
+// Blocking half of `op_read_key` on Unix: read and decode one key from `fd`,
+// returning it along with any unconsumed buffered bytes.
+#[cfg(unix)]
+fn read_key_unix(
+  fd: std::os::unix::io::RawFd,
+  table: &[(Vec<u8>, Key)],
+  mut buf: Vec<u8>,
+) -> Result<(Key, Vec<u8>), AnyError> {
+  loop {
+    if let Decoded::Key(key, consumed) = decode_unix(&buf, table) {
+      buf.drain(..consumed);
+      return Ok((key, buf));
+    }
+
+    // An escape sequence in progress (a lone ESC, or a prefix like `ESC [` /
+    // `ESC O`) that is not extended within a short window is the Esc key.
+    // Without this window a truncated sequence — Alt-O, a half-sent key —
+    // would block the following `read` forever. Emit Esc, consuming just the
+    // ESC byte and leaving any trailing bytes buffered for the next call.
+    if buf.first() == Some(&0x1b) && !poll_readable(fd, 50) {
+      buf.drain(..1);
+      return Ok((Key::Esc, buf));
+    }
+
+    let mut chunk = [0u8; 32];
+    let n = read_fd(fd, &mut chunk)?;
+    if n == 0 {
+      // EOF: surface whatever is buffered as an Esc rather than spinning.
+      buf.clear();
+      return Ok((Key::Esc, buf));
+    }
+    buf.extend_from_slice(&chunk[..n]);
+  }
+}
+
+// Read up to `buf.len()` bytes from a raw fd, mapping errors to `AnyError`.
+#[cfg(unix)]
+fn read_fd(
+  fd: std::os::unix::io::RawFd,
+  buf: &mut [u8],
+) -> Result<usize, AnyError> {
+  // SAFETY: `buf` is a valid writable slice of `buf.len()` bytes.
+  let n = unsafe {
+    libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+  };
+  if n < 0 {
+    Err(Error::last_os_error().into())
+  } else {
+    Ok(n as usize)
+  }
+}
+
+#[cfg(windows)]
+fn read_key_windows(win_tty_state: &WinTtyState) -> Result<Key, AnyError> {
+  use winapi::shared::minwindef::FALSE;
+  use winapi::um::consoleapi;
+  use winapi::um::processenv;
+  use winapi::um::winbase::STD_INPUT_HANDLE;
+  use winapi::um::wincon;
+  use winapi::um::winuser;
+
+  // SAFETY: winapi call; returns the process stdin handle.
+  let input = unsafe { processenv::GetStdHandle(STD_INPUT_HANDLE) };
+
+  loop {
+    let mut record: wincon::INPUT_RECORD = unsafe { std::mem::zeroed() };
+    let mut read: DWORD = 0;
+    // SAFETY: winapi call over a single INPUT_RECORD.
+    if unsafe {
+      consoleapi::ReadConsoleInputW(input, &mut record, 1, &mut read)
+    } == FALSE
+    {
+      // A concurrent `op_stdin_set_raw` may have aborted this read via
+      // `CancelIoEx` to switch the console mode. If so, acknowledge the new
+      // mode and re-issue the read so no keystrokes are lost; otherwise it is
+      // a genuine error.
+      if win_tty_state.take_cancel() {
+        win_tty_state.notify_ack();
+        continue;
+      }
+      return Err(Error::last_os_error().into());
+    }
+    if read != 1 || record.EventType != wincon::KEY_EVENT {
+      continue;
+    }
+    // SAFETY: EventType is KEY_EVENT, so the union holds a KEY_EVENT_RECORD.
+    let event = unsafe { record.Event.KeyEvent() };
+    if event.bKeyDown == FALSE {
+      continue;
+    }
+
+    let key = match event.wVirtualKeyCode as i32 {
+      winuser::VK_RETURN => Key::Enter,
+      winuser::VK_BACK => Key::Backspace,
+      winuser::VK_TAB => Key::Tab,
+      winuser::VK_ESCAPE => Key::Esc,
+      winuser::VK_UP => Key::Up,
+      winuser::VK_DOWN => Key::Down,
+      winuser::VK_LEFT => Key::Left,
+      winuser::VK_RIGHT => Key::Right,
+      winuser::VK_HOME => Key::Home,
+      winuser::VK_END => Key::End,
+      winuser::VK_PRIOR => Key::PageUp,
+      winuser::VK_NEXT => Key::PageDown,
+      winuser::VK_DELETE => Key::Delete,
+      vk if (winuser::VK_F1..=winuser::VK_F12).contains(&vk) => {
+        Key::F((vk - winuser::VK_F1 + 1) as u8)
+      }
+      _ => {
+        // SAFETY: union access of the UTF-16 code unit for this key event.
+        let unit = unsafe { *event.uChar.UnicodeChar() };
+        match char::from_u32(unit as u32) {
+          Some(ch) if !ch.is_control() => Key::Char(ch),
+          _ => continue,
+        }
+      }
+    };
+    return Ok(key);
+  }
+}
+
 #[op(fast)]
 fn op_console_size(
   state: &mut OpState,
   result: &mut [u32],
 ) -> Result<(), AnyError> {
-  fn check_console_size(
-    state: &mut OpState,
-    result: &mut [u32],
-    rid: u32,
-  ) -> Result<(), AnyError> {
-    StdFileResource::with_file(state, rid, move |std_file| {
-      let size = console_size(std_file)?;
-      result[0] = size.cols;
-      result[1] = size.rows;
-      Ok(())
-    })
+  let size = console_size_from_state(state)?;
+  result[0] = size.cols;
+  result[1] = size.rows;
+  Ok(())
+}
+
+// Resolves with the terminal size the next time it changes. Callers that
+// redraw on resize can await this instead of polling `op_console_size`.
+//
+// Cancelation: dropping the future tears down the SIGWINCH registration on
+// Unix immediately. On Windows `ReadConsoleInput` cannot be preempted, so the
+// reading thread is stopped cooperatively — a drop guard flips a flag that the
+// thread checks every time its short `WaitForSingleObject` poll wakes, so the
+// thread exits within that poll interval rather than being leaked.
+#[op]
+async fn op_console_next_size(
+  state: Rc<RefCell<OpState>>,
+) -> Result<ConsoleSize, AnyError> {
+  #[cfg(unix)]
+  {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    let mut sigwinch = signal(SignalKind::window_change())?;
+    sigwinch.recv().await;
+    console_size_from_state(&mut state.borrow_mut())
+  }
+  #[cfg(windows)]
+  {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    let _ = &state;
+    let cancel = Arc::new(AtomicBool::new(false));
+    // Signal the reading thread to stop as soon as the future is dropped.
+    let _guard = CancelGuard(cancel.clone());
+    tokio::task::spawn_blocking(move || wait_for_window_buffer_event(&cancel))
+      .await?
   }
+}
 
-  let mut last_result = Ok(());
-  // Since stdio might be piped we try to get the size of the console for all
-  // of them and return the first one that succeeds.
+// Flips a flag on drop so a cooperatively-cancelable blocking thread can stop.
+#[cfg(windows)]
+struct CancelGuard(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+#[cfg(windows)]
+impl Drop for CancelGuard {
+  fn drop(&mut self) {
+    self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+  }
+}
+
+#[cfg(windows)]
+fn wait_for_window_buffer_event(
+  cancel: &std::sync::atomic::AtomicBool,
+) -> Result<ConsoleSize, AnyError> {
+  use std::sync::atomic::Ordering;
+  use winapi::shared::minwindef::FALSE;
+  use winapi::um::consoleapi;
+  use winapi::um::processenv;
+  use winapi::um::synchapi::WaitForSingleObject;
+  use winapi::um::winbase::STD_INPUT_HANDLE;
+  use winapi::um::winbase::STD_OUTPUT_HANDLE;
+  use winapi::um::winbase::WAIT_OBJECT_0;
+  use winapi::um::wincon;
+  use winapi::um::wincontypes::INPUT_RECORD;
+
+  // SAFETY: winapi call; returns the process stdin handle.
+  let input = unsafe { processenv::GetStdHandle(STD_INPUT_HANDLE) };
+
+  let mut original_mode: DWORD = 0;
+  // SAFETY: winapi call
+  if unsafe { consoleapi::GetConsoleMode(input, &mut original_mode) } == FALSE {
+    return Err(Error::last_os_error().into());
+  }
+  // SAFETY: winapi call
+  if unsafe {
+    consoleapi::SetConsoleMode(input, original_mode | wincon::ENABLE_WINDOW_INPUT)
+  } == FALSE
+  {
+    return Err(Error::last_os_error().into());
+  }
+
+  let result = loop {
+    if cancel.load(Ordering::SeqCst) {
+      break Err(custom_error("Interrupted", "resize subscription cancelled"));
+    }
+    // Poll so cancelation is observed promptly rather than blocking in
+    // `ReadConsoleInput` until some unrelated input event arrives.
+    // SAFETY: winapi call on a live console input handle.
+    if unsafe { WaitForSingleObject(input, 100) } != WAIT_OBJECT_0 {
+      continue;
+    }
+
+    let mut record: INPUT_RECORD = unsafe { std::mem::zeroed() };
+    let mut read: DWORD = 0;
+    // SAFETY: winapi call; `record` is a single valid INPUT_RECORD.
+    if unsafe {
+      consoleapi::ReadConsoleInputW(input, &mut record, 1, &mut read)
+    } == FALSE
+    {
+      break Err(Error::last_os_error().into());
+    }
+    if read == 1 && record.EventType == wincon::WINDOW_BUFFER_SIZE_EVENT {
+      // SAFETY: CONSOLE_SCREEN_BUFFER_INFO is plain data; all-zero is valid.
+      let mut bufinfo: wincon::CONSOLE_SCREEN_BUFFER_INFO =
+        unsafe { std::mem::zeroed() };
+      // The input handle is not a screen buffer, so re-read the size through
+      // the process's standard output handle.
+      // SAFETY: winapi calls
+      if unsafe {
+        let output = processenv::GetStdHandle(STD_OUTPUT_HANDLE);
+        wincon::GetConsoleScreenBufferInfo(output, &mut bufinfo)
+      } == 0
+      {
+        break Err(Error::last_os_error().into());
+      }
+      break Ok(ConsoleSize {
+        cols: bufinfo.dwSize.X as u32,
+        rows: bufinfo.dwSize.Y as u32,
+      });
+    }
+  };
+
+  // Restore the mode so we do not leave window-input events enabled.
+  // SAFETY: winapi call
+  unsafe { consoleapi::SetConsoleMode(input, original_mode) };
+  result
+}
+
+// Try each stdio handle in turn since any of them might be piped, returning
+// the size of the first one that is a console.
+fn console_size_from_state(
+  state: &mut OpState,
+) -> Result<ConsoleSize, AnyError> {
+  let mut last_result = Err(Error::last_os_error().into());
   for rid in [0, 1, 2] {
-    last_result = check_console_size(state, result, rid);
+    last_result = StdFileResource::with_file(state, rid, move |std_file| {
+      Ok(console_size(std_file)?)
+    });
     if last_result.is_ok() {
-      return last_result;
+      break;
     }
   }
-
   last_result
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
 pub struct ConsoleSize {
   pub cols: u32,
   pub rows: u32,
@@ -269,20 +1067,11 @@ pub fn console_size(
 
   #[cfg(unix)]
   {
-    use std::os::unix::io::AsRawFd;
-
-    let fd = std_file.as_raw_fd();
-    // SAFETY: libc calls
-    unsafe {
-      let mut size: libc::winsize = std::mem::zeroed();
-      if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size as *mut _) != 0 {
-        return Err(Error::last_os_error());
-      }
-      Ok(ConsoleSize {
-        cols: size.ws_col as u32,
-        rows: size.ws_row as u32,
-      })
-    }
+    let size = rustix::termios::tcgetwinsize(std_file)?;
+    Ok(ConsoleSize {
+      cols: size.ws_col as u32,
+      rows: size.ws_row as u32,
+    })
   }
 }
 
@@ -312,4 +1101,30 @@ mod tests {
       mode_raw_input_off(mode_raw_input_on(known_off_modes[1]))
     );
   }
+
+  #[test]
+  fn test_winos_cbreak_mode_transitions() {
+    use crate::ops::tty::mode_cbreak_input_off;
+    use crate::ops::tty::mode_cbreak_input_on;
+    use winapi::um::wincon;
+
+    let known_off_modes =
+      [0xf7 /* Win10/CMD */, 0x1f7 /* Win10/WinTerm */];
+
+    for &off in &known_off_modes {
+      let on = mode_cbreak_input_on(off);
+
+      // cbreak keeps processed input (for Ctrl-C) but drops line editing/echo.
+      assert_eq!(on & wincon::ENABLE_PROCESSED_INPUT, wincon::ENABLE_PROCESSED_INPUT);
+      assert_eq!(on & wincon::ENABLE_LINE_INPUT, 0);
+      assert_eq!(on & wincon::ENABLE_ECHO_INPUT, 0);
+      assert_eq!(
+        on & wincon::ENABLE_VIRTUAL_TERMINAL_INPUT,
+        wincon::ENABLE_VIRTUAL_TERMINAL_INPUT
+      );
+
+      // ON-OFF round-trip is neutral for these known modes.
+      assert_eq!(off, mode_cbreak_input_off(on));
+    }
+  }
 }